@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use std::path::PathBuf;
 use structopt::StructOpt;
 
@@ -14,13 +15,80 @@ pub enum Action {
         #[structopt()]
         task: String,
     },
-    /// Remove an entry from the journal file by position.
+    /// Mark an entry as done without removing it from the journal.
     Done {
         #[structopt()]
         position: usize,
     },
-    /// List all tasks in the journal file.
-    List,
+    /// List tasks in the journal file.
+    ///
+    /// Positions printed alongside each task are always the task's position in
+    /// the full, unfiltered journal, so `done <n>` completes the same task the
+    /// list showed even when `--mode`/`--contains`/`--after`/`--before` narrow
+    /// what's displayed.
+    List {
+        /// Which tasks to show: `all`, `open`, or `done`.
+        #[structopt(long, default_value = "all")]
+        mode: ListMode,
+
+        /// Only show tasks whose text contains this substring (case-insensitive).
+        #[structopt(long)]
+        contains: Option<String>,
+
+        /// Only show tasks created on or after this RFC 3339 timestamp.
+        #[structopt(long)]
+        after: Option<DateTime<Utc>>,
+
+        /// Only show tasks created on or before this RFC 3339 timestamp.
+        #[structopt(long)]
+        before: Option<DateTime<Utc>>,
+    },
+}
+
+/// Selects which subset of the journal `List` prints.
+#[derive(Debug)]
+pub enum ListMode {
+    All,
+    Open,
+    Done,
+}
+
+impl std::str::FromStr for ListMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode.to_lowercase().as_str() {
+            "all" => Ok(ListMode::All),
+            "open" => Ok(ListMode::Open),
+            "done" => Ok(ListMode::Done),
+            other => Err(format!(
+                "invalid list mode `{}` (expected `all`, `open`, or `done`)",
+                other
+            )),
+        }
+    }
+}
+
+/// Selects how `list_tasks` renders its output.
+#[derive(Debug)]
+pub enum Format {
+    Plain,
+    Json,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.to_lowercase().as_str() {
+            "plain" => Ok(Format::Plain),
+            "json" => Ok(Format::Json),
+            other => Err(format!(
+                "invalid format `{}` (expected `plain` or `json`)",
+                other
+            )),
+        }
+    }
 }
 
 /*
@@ -47,4 +115,8 @@ pub struct CommandLineArgs {
     /// Use a different journal file.
     #[structopt(parse(from_os_str), short, long)]
     pub journal_file: Option<PathBuf>,
+
+    /// Output format for `list`: `plain` or `json`.
+    #[structopt(long, default_value = "plain")]
+    pub format: Format,
 }
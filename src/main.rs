@@ -35,6 +35,7 @@ fn main() -> anyhow::Result<()> {
     let CommandLineArgs {
         action,
         journal_file,
+        format,
     } = CommandLineArgs::from_args();
 
     /*
@@ -46,7 +47,12 @@ fn main() -> anyhow::Result<()> {
 
     match action {
         Add { task } => tasks::add_task(journal_file, Task::new(task)),
-        List => tasks::list_tasks(journal_file),
+        List {
+            mode,
+            contains,
+            after,
+            before,
+        } => tasks::list_tasks(journal_file, mode, format, contains, after, before),
         Done { position } => tasks::complete_task(journal_file, position),
     }?;
 
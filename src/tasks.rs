@@ -1,10 +1,17 @@
-use chrono::{serde::ts_seconds, DateTime, Local, Utc};
+use chrono::{
+    serde::{ts_seconds, ts_seconds_option},
+    DateTime, Local, Utc,
+};
 use serde::Deserialize;
 use serde::Serialize;
+use fs2::FileExt;
 use std::fmt;
 use std::fs::{File, OpenOptions};
-use std::path::PathBuf,
+use std::path::{Path, PathBuf};
 use std::io::{Error, ErrorKind, Result, Seek, SeekFrom};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cli::{Format, ListMode};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Task {
@@ -18,12 +25,72 @@ pub struct Task {
      */
     #[serde(with = "ts_seconds")]
     pub created_at: DateTime<Utc>,
+
+    /*
+     * `None` while the task is still open. Set to `Some(Utc::now())` by `complete_task`
+     * so the history keeps a record of when a task was finished instead of discarding it.
+     */
+    #[serde(with = "ts_seconds_option", default)]
+    pub completed_at: Option<DateTime<Utc>>,
 }
 
 impl Task {
     pub fn new(text: String) -> Task {
         let created_at: DateTime<Utc> = Utc::now();
-        Task { text, created_at }
+        Task {
+            text,
+            created_at,
+            completed_at: None,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.completed_at.is_some()
+    }
+}
+
+/*
+ * The on-disk format of the journal file. Wrapping the task list in a
+ * versioned envelope means a future field or format change can add an
+ * upgrade step in `migrate` instead of breaking everyone's existing
+ * `.rusty-journal.json`.
+ */
+const CURRENT_JOURNAL_VERSION: u32 = 1;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Journal {
+    pub version: u32,
+    pub tasklist: Vec<Task>,
+}
+
+impl Journal {
+    fn new(tasklist: Vec<Task>) -> Journal {
+        Journal {
+            version: CURRENT_JOURNAL_VERSION,
+            tasklist,
+        }
+    }
+}
+
+/*
+ * Bring a freshly-read journal up to `CURRENT_JOURNAL_VERSION`. There's only
+ * one version today, so this is a no-op for `CURRENT_JOURNAL_VERSION`, but it
+ * gives future format changes (e.g. adding priorities or tags) a single place
+ * to add a `version => { .. }` upgrade step instead of hand-rolling migration
+ * at every call site. A version we don't recognize (e.g. a newer journal read
+ * by an older binary) is rejected rather than passed through as-is, since
+ * accepting it silently would risk misreading a format we don't understand.
+ */
+fn migrate(journal: Journal) -> Result<Journal> {
+    match journal.version {
+        CURRENT_JOURNAL_VERSION => Ok(journal),
+        other => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "unsupported journal version {} (expected {})",
+                other, CURRENT_JOURNAL_VERSION
+            ),
+        )),
     }
 }
 
@@ -43,15 +110,24 @@ impl fmt::Display for Task {
      */
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let created_at = self.created_at.with_timezone(&Local).format("%F %H:%M");
+        let checkbox = if self.is_complete() { "[x]" } else { "[ ]" };
+
         /*
          * `{:<50}`: a left-aligned string padded with 50 spaces.
          * `[{}]`: the date and time the task was created, inside brackets.
          */
-        write!(f, "{:<50} [{}]", self.text, created_at);
+        write!(f, "{} {:<50} [{}]", checkbox, self.text, created_at)?;
+
+        if let Some(completed_at) = self.completed_at {
+            let completed_at = completed_at.with_timezone(&Local).format("%F %H:%M");
+            write!(f, " (done {})", completed_at)?;
+        }
+
+        Ok(())
     }
 }
 
-fn collect_tasks(mut file: &File) -> Result<Vec<Task>> {
+fn collect_tasks(mut file: &File) -> Result<Journal> {
     /*
      * Rewind the file after reading from it.
      * `file.seek(...)` is used to move the cursor to a specific position in the file.
@@ -63,104 +139,357 @@ fn collect_tasks(mut file: &File) -> Result<Vec<Task>> {
     file.seek(SeekFrom::Start(0))?;
 
     /*
-     * Consume the file's contents as a vector of tasks.
-     * `match serde_json::from_reader(&file)` reads the contents of a file
-     * and attempts to convert (deserialize) it into a vector of type Task.
-     * Here the `serde_json::from_reader(&file)` function takes a file pointer `&file` as an argument
-     * and attempts to convert the JSON-format content of the file into a Rust data structure.
+     * Consume the file's contents as a `Journal`.
+     * `match serde_json::from_reader(file)` reads the contents of a file
+     * and attempts to convert (deserialize) it into a `Journal`.
+     * Here the `serde_json::from_reader(file)` function takes a file pointer
+     * as an argument and attempts to convert the JSON-format content of the
+     * file into a Rust data structure.
      */
-    let tasks = match serde_json::from_reader(file) {
-        Ok(tasks) => tasks,
+    let journal = match serde_json::from_reader::<_, Journal>(file) {
+        Ok(journal) => journal,
 
         /*
          * If an error occurs but the error indicates that the end of the file (EOF: End Of File) has been reached,
-         * an empty vector (`Vec::new()`) is initialized as tasks.
+         * an empty journal at the current version is initialized.
          * This is used when the file is empty or the correct data has not yet been written.
          */
-        Err(e) if e.is_eof => Vec::new(),
-        Err(e) => Err(e)?,
+        Err(e) if e.is_eof() => Journal::new(Vec::new()),
+
+        /*
+         * Not a versioned journal. Files written before this envelope existed
+         * are a bare task array, so rewind and retry as that legacy shape
+         * before giving up, lifting it into `version: 1` on success.
+         */
+        Err(_) => {
+            file.seek(SeekFrom::Start(0))?;
+            match serde_json::from_reader::<_, Vec<Task>>(file) {
+                Ok(tasklist) => Journal::new(tasklist),
+                Err(e) if e.is_eof() => Journal::new(Vec::new()),
+                Err(e) => Err(e)?,
+            }
+        }
     };
     file.seek(SeekFrom::Start(0))?;
-    Ok(tasks);
+    migrate(journal)
 }
 
-pub fn add_task(journal_path: PathBuf, task: Task) -> Result<()> {
-    /*
-     * Open the file.
-     * The question mark symbol (`?`) after that statement is used to propagate errors without writing too much boilerplate code.
-     * It's syntax sugar for early returning an error if that error matches with the return type of the function it's in.
-     * So below snippets are equivalent:
-     * fn function_1() -> Result(Success, Failure) {
-     *      match operation_that_might_fail() {
-     *          Ok(success) => success,
-     *          Err(failure) => return Err(failure),
-     *      }
-     *  }
-     *
-     *  fn function_2() -> Result(Success, Failure) {
-     *      operation_that_might_fail()?
-     *  }
-     *
-     * See doc: https://doc.rust-lang.org/reference/expressions/operator-expr.html#:~:text=The%20question%20mark%20operator%20(%20%3F%20),%3E%20type%2C%20it%20propagates%20errors.
-     */
-    let mut file = OpenOptions::new()
+/*
+ * Build the path of a file that sits next to the journal, e.g. turning
+ * `foo.json` into `foo.json.lock`.
+ */
+fn sibling_path(journal_path: &Path, suffix: &str) -> Result<PathBuf> {
+    match journal_path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => Ok(journal_path.with_file_name(format!("{}.{}", name, suffix))),
+        None => Err(Error::new(ErrorKind::InvalidInput, "Invalid journal path")),
+    }
+}
+
+/*
+ * Run `f` while holding an advisory exclusive lock on a dedicated `.lock`
+ * file next to the journal. Unlike locking the journal file itself, this
+ * path is never replaced by a `rename`, so the lock keeps protecting the
+ * journal for the whole critical section instead of being silently dropped
+ * the instant `write_tasks` swaps the journal out from under it.
+ */
+fn with_journal_lock<T>(journal_path: &Path, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let lock_file = OpenOptions::new()
         .read(true)
         .write(true)
         .create(true)
-        .open(journal_path)?;
+        .truncate(false)
+        .open(sibling_path(journal_path, "lock")?)?;
+    lock_file.lock_exclusive()?;
 
-    let mut tasks = collect_tasks(&file)?;
+    let result = f();
 
-    /*
-     * Write the modified task list back into the file.
-     */
-    tasks.push(task);
-    serde_json::to_write(file, &tasks)?;
+    lock_file.unlock()?;
+    result
+}
 
-    /*
-     * `()` is called `unit`.
-     * If no return type is specified for the function, it returns an empty tuple(`()`).
-     */
-    Ok(());
+/*
+ * Write `journal` out without ever leaving the journal file in a half-written
+ * state: the new contents land in a sibling temp file first, then `rename`
+ * swaps it into place. A rename onto an existing path is atomic on the
+ * platforms we support, so readers only ever see the old file or the
+ * fully-written new one, never a truncated one. The temp path is suffixed
+ * with this process's pid and the current time so that two writers racing
+ * under the same lock (e.g. a stale lock from a crashed process) can't stomp
+ * each other's temp file. Writers always emit `CURRENT_JOURNAL_VERSION`,
+ * regardless of what version was read.
+ */
+fn write_tasks(journal_path: &Path, journal: &Journal) -> Result<()> {
+    let unique = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default();
+    let tmp_path = sibling_path(journal_path, &format!("tmp.{}.{}", std::process::id(), unique))?;
+
+    let tmp_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&tmp_path)?;
+    serde_json::to_writer(tmp_file, journal)?;
+
+    std::fs::rename(&tmp_path, journal_path)?;
+
+    Ok(())
+}
+
+pub fn add_task(journal_path: PathBuf, task: Task) -> Result<()> {
+    with_journal_lock(&journal_path, || {
+        /*
+         * Only ever read through this handle — all writing happens via
+         * `write_tasks`'s temp-file-plus-rename below. `write(true)` is still
+         * required here because `create(true)` needs write access to actually
+         * create the file when the journal doesn't exist yet; `truncate(false)`
+         * makes explicit that we don't want that creation to blow away an
+         * existing journal's contents.
+         */
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&journal_path)?;
+
+        let mut journal = collect_tasks(&file)?;
+        journal.tasklist.push(task);
+
+        write_tasks(&journal_path, &journal)
+    })
 }
 
 pub fn complete_task(journal_path: PathBuf, task_position: usize) -> Result<()> {
-    let file = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .open(journal_path)?;
+    with_journal_lock(&journal_path, || {
+        let file = OpenOptions::new().read(true).open(&journal_path)?;
+        let mut journal = collect_tasks(&file)?;
 
-    let tasks = collect_tasks(&file)?;
+        if task_position == 0 || task_position > journal.tasklist.len() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Invalid Task ID"));
+        }
 
-    if task_position == 0 || task_position > tasks.len() {
-        return Err(Error::new(ErrorKind::InvalidInput, "Invalid Task ID"));
-    }
-    tasks.remove(task_position - 1);
+        /*
+         * Mark the task as done instead of removing it, so its history (when it
+         * was created and when it was finished) stays in the journal.
+         */
+        journal.tasklist[task_position - 1].completed_at = Some(Utc::now());
 
-    /*
-     * Write the modified task list back into the file.
-     * `file.set_len(0)?` sets the size of the file to 0 bytes. This completely deletes the existing contents of the file.
-     * This procedure is used to empty a file before completely replacing its contents with new data.
-     */
-    file.set_len(0)?;
-    serde_json::to_write(file, &tasks)?;
+        write_tasks(&journal_path, &journal)
+    })
+}
+
+/*
+ * The shape emitted in `--format json` mode: the human-facing `Display` impl
+ * does not include the position and renders completion only as a checkbox, so
+ * we serialize the position and a `complete` flag explicitly for tools that
+ * want to consume the list programmatically.
+ */
+#[derive(Serialize)]
+struct TaskRecord<'a> {
+    index: u32,
+    text: &'a str,
+    #[serde(with = "ts_seconds")]
+    created_at: DateTime<Utc>,
+    complete: bool,
+}
+
+/*
+ * Number every task by its position in the unfiltered journal *before*
+ * filtering, so a position returned here always matches what `done <n>`
+ * (which indexes straight into the unfiltered journal) will complete.
+ */
+fn filtered_tasks(
+    tasklist: Vec<Task>,
+    mode: ListMode,
+    contains: &Option<String>,
+    after: Option<DateTime<Utc>>,
+    before: Option<DateTime<Utc>>,
+) -> Vec<(usize, Task)> {
+    tasklist
+        .into_iter()
+        .enumerate()
+        .map(|(i, task)| (i + 1, task))
+        .filter(|(_, task)| match mode {
+            ListMode::All => true,
+            ListMode::Open => !task.is_complete(),
+            ListMode::Done => task.is_complete(),
+        })
+        .filter(|(_, task)| match contains {
+            Some(substring) => task
+                .text
+                .to_lowercase()
+                .contains(&substring.to_lowercase()),
+            None => true,
+        })
+        .filter(|(_, task)| after.is_none_or(|after| task.created_at >= after))
+        .filter(|(_, task)| before.is_none_or(|before| task.created_at <= before))
+        .collect()
+}
 
-    Ok(());
+fn to_records<'a>(tasks: &'a [(usize, Task)]) -> Vec<TaskRecord<'a>> {
+    tasks
+        .iter()
+        .map(|(position, task)| TaskRecord {
+            index: *position as u32,
+            text: &task.text,
+            created_at: task.created_at,
+            complete: task.is_complete(),
+        })
+        .collect()
 }
 
-pub fn list_tasks(journal_path: PathBuf) -> Result<()> {
+pub fn list_tasks(
+    journal_path: PathBuf,
+    mode: ListMode,
+    format: Format,
+    contains: Option<String>,
+    after: Option<DateTime<Utc>>,
+    before: Option<DateTime<Utc>>,
+) -> Result<()> {
     let file = OpenOptions::new().read(true).open(journal_path)?;
-    let tasks = collect_tasks(&file)?;
-
-    if tasks.is_empty() {
-        println!("Task list is empty!");
-    } else {
-        let mut order: u32 = 1;
-        for task in tasks {
-            println!("{}: {}", order, task);
-            order += 1;
+    let tasks = filtered_tasks(collect_tasks(&file)?.tasklist, mode, &contains, after, before);
+
+    match format {
+        Format::Json => {
+            serde_json::to_writer(std::io::stdout(), &to_records(&tasks))?;
+        }
+        Format::Plain => {
+            if tasks.is_empty() {
+                println!("Task list is empty!");
+            } else {
+                for (position, task) in tasks {
+                    println!("{}: {}", position, task);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn journal_file(contents: &str) -> File {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file
+    }
+
+    #[test]
+    fn display_renders_checkbox_and_completion_time() {
+        let mut task = Task::new("write tests".to_string());
+        assert!(!task.is_complete());
+        assert!(format!("{}", task).starts_with("[ ] write tests"));
+        assert!(!format!("{}", task).contains("(done "));
+
+        task.completed_at = Some(Utc::now());
+        assert!(task.is_complete());
+        assert!(format!("{}", task).starts_with("[x] write tests"));
+        assert!(format!("{}", task).contains("(done "));
+    }
+
+    #[test]
+    fn collect_tasks_migrates_legacy_bare_array() {
+        let file = journal_file(
+            r#"[{"text":"alpha","created_at":1700000000,"completed_at":null}]"#,
+        );
+
+        let journal = collect_tasks(&file).unwrap();
+
+        assert_eq!(journal.version, CURRENT_JOURNAL_VERSION);
+        assert_eq!(journal.tasklist.len(), 1);
+        assert_eq!(journal.tasklist[0].text, "alpha");
+    }
+
+    #[test]
+    fn filtered_list_positions_match_unfiltered_journal_index() {
+        /*
+         * `done 2` indexes straight into the unfiltered journal, so it
+         * completes "beta" here. Every position `filtered_tasks` reports
+         * must agree with that indexing, regardless of which mode narrows
+         * what's shown — "beta" must stay at position 2 in every view.
+         */
+        fn tasklist_with_beta_done() -> Vec<Task> {
+            let mut tasklist = vec![
+                Task::new("alpha".to_string()),
+                Task::new("beta".to_string()),
+                Task::new("gamma".to_string()),
+            ];
+            tasklist[1].completed_at = Some(Utc::now());
+            tasklist
+        }
+
+        fn positions(tasks: Vec<(usize, Task)>) -> Vec<(usize, String)> {
+            tasks
+                .into_iter()
+                .map(|(position, task)| (position, task.text))
+                .collect()
         }
+
+        let open = filtered_tasks(tasklist_with_beta_done(), ListMode::Open, &None, None, None);
+        assert_eq!(
+            positions(open),
+            vec![(1, "alpha".to_string()), (3, "gamma".to_string())]
+        );
+
+        let done = filtered_tasks(tasklist_with_beta_done(), ListMode::Done, &None, None, None);
+        assert_eq!(positions(done), vec![(2, "beta".to_string())]);
+    }
+
+    #[test]
+    fn json_format_serializes_index_text_created_at_and_complete() {
+        let mut task = Task::new("ship it".to_string());
+        task.completed_at = Some(Utc::now());
+
+        let tasks = [(1, task)];
+        let records = to_records(&tasks);
+        let json = serde_json::to_string(&records).unwrap();
+
+        assert_eq!(
+            json,
+            format!(
+                r#"[{{"index":1,"text":"ship it","created_at":{},"complete":true}}]"#,
+                records[0].created_at.timestamp()
+            )
+        );
+    }
+
+    #[test]
+    fn migrate_rejects_unknown_version() {
+        let journal = Journal {
+            version: CURRENT_JOURNAL_VERSION + 1,
+            tasklist: Vec::new(),
+        };
+
+        assert!(migrate(journal).is_err());
     }
 
-    Ok(());
+    #[test]
+    fn concurrent_add_task_does_not_lose_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal_path = dir.path().join("journal.json");
+
+        let handles: Vec<_> = (0..20)
+            .map(|i| {
+                let journal_path = journal_path.clone();
+                std::thread::spawn(move || {
+                    add_task(journal_path, Task::new(format!("task {}", i))).unwrap();
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let file = OpenOptions::new().read(true).open(&journal_path).unwrap();
+        let journal = collect_tasks(&file).unwrap();
+        assert_eq!(journal.tasklist.len(), 20);
+    }
 }